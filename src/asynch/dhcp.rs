@@ -1,11 +1,15 @@
 use crate::dhcp;
 
+use embedded_nal_async::Ipv4Addr;
+
 #[derive(Debug)]
 pub enum Error<E> {
     Io(E),
     Format(dhcp::Error),
     Timeout,
     Nak,
+    /// A `ConflictDetector` found the address already in use on the link.
+    Conflict,
 }
 
 impl<E> From<dhcp::Error> for Error<E> {
@@ -14,6 +18,28 @@ impl<E> From<dhcp::Error> for Error<E> {
     }
 }
 
+/// Probes whether an address is already live on the link, so a `Client`
+/// can decline a lease it was offered, or a `Server` can avoid granting
+/// one, for an address another host has already claimed.
+///
+/// Implemented by users with whatever ARP/ping facility their network
+/// stack exposes, since this crate is built on `embedded-nal-async` and
+/// has no raw L2 access of its own.
+pub trait ConflictDetector {
+    async fn is_in_use(&mut self, ip: Ipv4Addr) -> bool;
+}
+
+/// A `ConflictDetector` that always reports an address as free,
+/// preserving the crate's previous behavior of trusting the peer.
+#[derive(Default)]
+pub struct NoopConflictDetector;
+
+impl ConflictDetector for NoopConflictDetector {
+    async fn is_in_use(&mut self, _ip: Ipv4Addr) -> bool {
+        false
+    }
+}
+
 pub mod client {
     use core::fmt::Debug;
 
@@ -29,6 +55,18 @@ pub mod client {
     pub use super::*;
     pub use crate::dhcp::Settings;
 
+    /// Fallback lease time, used only when a server grants a lease without
+    /// sending an explicit lease-time option.
+    const DEFAULT_LEASE_SECS: u64 = 86400;
+
+    /// Tracks where a lease sits in the RFC 2131 renewal timeline.
+    #[derive(Clone, Copy, Debug, PartialEq, Eq)]
+    enum LeaseState {
+        Bound,
+        Renewing,
+        Rebinding,
+    }
+
     #[derive(Clone, Debug)]
     pub struct Configuration {
         pub mac: [u8; 6],
@@ -46,11 +84,12 @@ pub mod client {
         }
     }
 
-    pub struct Client<R> {
+    pub struct Client<R, D = NoopConflictDetector> {
         rng: R,
         mac: [u8; 6],
         retries: usize,
         timeout: Duration,
+        detector: D,
     }
 
     impl<R> Client<R>
@@ -58,11 +97,22 @@ pub mod client {
         R: RngCore,
     {
         pub fn new(rng: R, conf: &Configuration) -> Self {
+            Self::with_detector(rng, conf, NoopConflictDetector)
+        }
+    }
+
+    impl<R, D> Client<R, D>
+    where
+        R: RngCore,
+        D: ConflictDetector,
+    {
+        pub fn with_detector(rng: R, conf: &Configuration, detector: D) -> Self {
             Self {
                 rng,
                 mac: conf.mac,
                 retries: conf.retries,
                 timeout: conf.timeout,
+                detector,
             }
         }
 
@@ -96,6 +146,98 @@ pub mod client {
             buf: &mut [u8],
             server_ip: Ipv4Addr,
             our_ip: Ipv4Addr,
+        ) -> Result<Settings, Error<U::Error>> {
+            // First-time acquisition of `our_ip`: probe it for conflicts.
+            self.renew(udp, buf, Some(server_ip), our_ip, true).await
+        }
+
+        /// Keeps a lease granted by `discover`/`request` alive for as long as
+        /// the server allows it, renewing and rebinding it per the RFC 2131
+        /// T1/T2 timers, and returns only once the lease can no longer be
+        /// renewed, at which point the caller should drop `settings.ip`.
+        pub async fn run<U: UdpStack>(
+            &mut self,
+            udp: &mut U,
+            buf: &mut [u8],
+            mut settings: Settings,
+        ) -> Result<(), Error<U::Error>> {
+            loop {
+                settings = self.renew_lease(udp, buf, settings).await?;
+            }
+        }
+
+        async fn renew_lease<U: UdpStack>(
+            &mut self,
+            udp: &mut U,
+            buf: &mut [u8],
+            settings: Settings,
+        ) -> Result<Settings, Error<U::Error>> {
+            let our_ip = settings.ip;
+            let server_ip = settings.server_ip.unwrap();
+
+            let lease_secs = settings.lease_time_secs.unwrap_or(DEFAULT_LEASE_SECS) as u64;
+            let t1_secs = settings
+                .renewal_time_secs
+                .map(|secs| secs as u64)
+                .unwrap_or(lease_secs / 2);
+            let t2_secs = settings
+                .rebinding_time_secs
+                .map(|secs| secs as u64)
+                .unwrap_or(lease_secs * 7 / 8);
+
+            let start = Instant::now();
+            let mut state = LeaseState::Bound;
+
+            loop {
+                let elapsed = (Instant::now() - start).as_secs();
+
+                let deadline = match state {
+                    LeaseState::Bound => t1_secs,
+                    LeaseState::Renewing => t2_secs,
+                    LeaseState::Rebinding => lease_secs,
+                };
+
+                Timer::after(Duration::from_secs(deadline.saturating_sub(elapsed))).await;
+
+                // Act the moment each deadline is reached, rather than
+                // waiting for the *next* one: T1 unicasts a renewal
+                // immediately, and only on failure do we wait for T2 to
+                // broadcast a rebind.
+                // Renewing and rebinding both re-request the address we
+                // already hold, so there's no need to re-probe it for
+                // conflicts; only a first-time `request` does that.
+                match state {
+                    LeaseState::Bound => {
+                        match self.renew(udp, buf, Some(server_ip), our_ip, false).await {
+                            Ok(settings) => return Ok(settings),
+                            Err(_) => state = LeaseState::Renewing,
+                        }
+                    }
+                    LeaseState::Renewing => {
+                        match self.renew(udp, buf, None, our_ip, false).await {
+                            Ok(settings) => return Ok(settings),
+                            Err(_) => state = LeaseState::Rebinding,
+                        }
+                    }
+                    LeaseState::Rebinding => return Err(Error::Timeout),
+                }
+            }
+        }
+
+        /// Sends a Request and waits for the server's reply.
+        ///
+        /// `probe` should only be set for a first-time acquisition of
+        /// `our_ip` (via `request`): renewing or rebinding a lease we
+        /// already hold re-requests an address we are actively using
+        /// ourselves, so probing it again would just rediscover our own
+        /// traffic and cause us to decline a perfectly good lease.
+        async fn renew<U: UdpStack>(
+            &mut self,
+            udp: &mut U,
+            buf: &mut [u8],
+            server_ip: Option<Ipv4Addr>,
+            our_ip: Ipv4Addr,
+            probe: bool,
         ) -> Result<Settings, Error<U::Error>> {
             let mut opt_buf = Options::buf();
 
@@ -103,7 +245,7 @@ pub mod client {
                 .send(
                     udp,
                     buf,
-                    Some(server_ip),
+                    server_ip,
                     Some(our_ip),
                     Options::request(our_ip, &mut opt_buf),
                     &[MessageType::Ack, MessageType::Nak],
@@ -111,11 +253,18 @@ pub mod client {
                 .await?
                 .unwrap();
 
-            if matches!(mt, MessageType::Ack) {
-                Ok(settings)
-            } else {
-                Err(Error::Nak)
+            if !matches!(mt, MessageType::Ack) {
+                return Err(Error::Nak);
             }
+
+            if probe && self.detector.is_in_use(settings.ip).await {
+                self.decline(udp, buf, settings.server_ip.unwrap(), settings.ip)
+                    .await?;
+
+                return Err(Error::Conflict);
+            }
+
+            Ok(settings)
         }
 
         pub async fn release<U: UdpStack>(
@@ -162,6 +311,32 @@ pub mod client {
             Ok(())
         }
 
+        /// Asks a server for configuration (DNS, gateway, NTP, etc.) for a
+        /// host that already has `ciaddr` statically configured, without
+        /// allocating or tracking a lease.
+        pub async fn inform<U: UdpStack>(
+            &mut self,
+            udp: &mut U,
+            buf: &mut [u8],
+            ciaddr: Ipv4Addr,
+        ) -> Result<Settings, Error<U::Error>> {
+            let mut opt_buf = Options::buf();
+
+            let (_, settings) = self
+                .send(
+                    udp,
+                    buf,
+                    None,
+                    Some(ciaddr),
+                    Options::inform(&mut opt_buf),
+                    &[MessageType::Ack],
+                )
+                .await?
+                .unwrap();
+
+            Ok(settings)
+        }
+
         async fn send<U: UdpStack>(
             &mut self,
             udp: &mut U,
@@ -222,6 +397,148 @@ pub mod client {
             Err(Error::Timeout)
         }
     }
+
+    // Exercises the T1/T2 timing directly, since getting it right is the
+    // whole point of `renew_lease`. Drives `embassy_time`'s mock clock so
+    // the deadlines can be asserted exactly instead of approximated with
+    // real sleeps, and uses a transport that never replies so every
+    // attempt times out and the next deadline is reached deterministically.
+    // Needs the `embassy-time/mock-driver` and `futures-lite` dev-deps.
+    #[cfg(test)]
+    mod tests {
+        use core::cell::RefCell;
+        use core::convert::Infallible;
+
+        use std::vec::Vec;
+
+        use embassy_time::MockDriver;
+
+        use super::*;
+
+        struct SilentSocket;
+
+        impl ConnectedUdp for SilentSocket {
+            type Error = Infallible;
+
+            async fn send(&mut self, _data: &[u8]) -> Result<(), Self::Error> {
+                Ok(())
+            }
+
+            async fn receive_into(&mut self, _buf: &mut [u8]) -> Result<usize, Self::Error> {
+                core::future::pending().await
+            }
+        }
+
+        /// Accepts every send but never answers, so each `send()` always
+        /// exhausts its retries. Records the virtual time of every connect
+        /// attempt, which is when we can observe a renew/rebind firing.
+        struct SilentUdp {
+            attempts: RefCell<Vec<Instant>>,
+        }
+
+        impl UdpStack for SilentUdp {
+            type Error = Infallible;
+            type UniquelyBound<'a> = SilentSocket;
+            type MultiplyBound<'a> = SilentSocket;
+            type Connected<'a> = SilentSocket;
+
+            async fn connect_from(
+                &self,
+                local: SocketAddr,
+                _remote: SocketAddr,
+            ) -> Result<(SocketAddr, Self::Connected<'_>), Self::Error> {
+                self.attempts.borrow_mut().push(Instant::now());
+
+                Ok((local, SilentSocket))
+            }
+
+            async fn bind_single(
+                &self,
+                local: SocketAddr,
+            ) -> Result<(SocketAddr, Self::UniquelyBound<'_>), Self::Error> {
+                Ok((local, SilentSocket))
+            }
+
+            async fn bind_multiple(
+                &self,
+                _local: SocketAddr,
+            ) -> Result<Self::MultiplyBound<'_>, Self::Error> {
+                Ok(SilentSocket)
+            }
+        }
+
+        struct FixedRng;
+
+        impl RngCore for FixedRng {
+            fn next_u32(&mut self) -> u32 {
+                0x1234
+            }
+
+            fn next_u64(&mut self) -> u64 {
+                0x1234_5678
+            }
+
+            fn fill_bytes(&mut self, dest: &mut [u8]) {
+                dest.fill(0);
+            }
+
+            fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand_core::Error> {
+                self.fill_bytes(dest);
+                Ok(())
+            }
+        }
+
+        #[test]
+        fn renew_fires_at_t1_and_rebind_fires_at_t2() {
+            MockDriver::get().reset();
+            MockDriver::get().set_auto_advance(true);
+
+            let conf = Configuration {
+                retries: 1,
+                timeout: Duration::from_secs(1),
+                ..Configuration::new([0, 1, 2, 3, 4, 5])
+            };
+            let mut client = Client::new(FixedRng, &conf);
+
+            let mut udp = SilentUdp {
+                attempts: RefCell::new(Vec::new()),
+            };
+            let mut buf = [0u8; 576];
+
+            let settings = Settings {
+                ip: Ipv4Addr::new(192, 168, 1, 10),
+                server_ip: Some(Ipv4Addr::new(192, 168, 1, 1)),
+                lease_time_secs: Some(1000),
+                renewal_time_secs: Some(500),
+                rebinding_time_secs: Some(875),
+                ..Default::default()
+            };
+
+            let start = Instant::now();
+
+            let result = futures_lite::future::block_on(
+                client.renew_lease(&mut udp, &mut buf, settings),
+            );
+            assert!(result.is_err());
+
+            let attempts = udp.attempts.into_inner();
+            assert!(
+                attempts.len() >= 2,
+                "expected both a renew attempt and a rebind attempt, got {attempts:?}"
+            );
+
+            assert_eq!(
+                (attempts[0] - start).as_secs(),
+                500,
+                "the unicast renew must fire at T1, not T2"
+            );
+            assert_eq!(
+                (attempts[1] - start).as_secs(),
+                875,
+                "the broadcast rebind must fire at T2, not at full lease expiry"
+            );
+        }
+    }
 }
 
 pub mod server {
@@ -235,7 +552,7 @@ pub mod server {
     pub use super::*;
 
     #[derive(Clone, Debug)]
-    pub struct Configuration {
+    pub struct Configuration<'a> {
         pub ip: Ipv4Addr,
         pub gateway: Option<Ipv4Addr>,
         pub subnet: Option<Ipv4Addr>,
@@ -244,36 +561,294 @@ pub mod server {
         pub range_start: Ipv4Addr,
         pub range_end: Ipv4Addr,
         pub lease_duration: Duration,
+        /// Options served in addition to router/subnet/DNS/lease-time (e.g.
+        /// domain name, NTP servers, MTU), and only to clients whose
+        /// Parameter Request List asks for them.
+        pub extra_options: &'a [DhcpOption<'a>],
+        /// How long a DECLINEd address is kept out of the available set
+        /// before it can be offered again.
+        pub decline_quarantine: Duration,
     }
 
+    #[derive(Clone, Copy)]
     struct Lease {
         mac: [u8; 6],
         expires: Instant,
     }
 
-    pub struct Server<const N: usize> {
+    #[derive(Clone, Copy)]
+    enum Slot {
+        Available,
+        Allocated(Lease),
+        Unavailable { until: Instant },
+    }
+
+    /// The set of addresses in a `Server`'s range, partitioned into
+    /// available, allocated (active leases) and unavailable (declined or
+    /// otherwise conflicting) addresses.
+    ///
+    /// Backed by one `Slot` per address in the range rather than a sparse
+    /// map, so a DECLINEd address can be quarantined instead of silently
+    /// falling back to "available" the moment its lease entry is gone.
+    struct AddressPool<const N: usize> {
+        range_start: Ipv4Addr,
+        slots: heapless::Vec<Slot, N>,
+        quarantine: Duration,
+    }
+
+    impl<const N: usize> AddressPool<N> {
+        fn new(range_start: Ipv4Addr, range_end: Ipv4Addr, quarantine: Duration) -> Self {
+            let start: u32 = range_start.into();
+            let end: u32 = range_end.into();
+
+            assert!(
+                start <= end,
+                "range_start ({range_start:?}) must not be after range_end ({range_end:?})",
+            );
+
+            let range_size = (end - start + 1) as usize;
+
+            assert!(
+                range_size <= N,
+                "address pool capacity N ({N}) is smaller than the configured range ({range_size} addresses); \
+                 N must be at least as large as range_end - range_start + 1, or addresses would be silently dropped",
+            );
+
+            let mut slots = heapless::Vec::new();
+
+            for _ in start..=end {
+                let _ = slots.push(Slot::Available);
+            }
+
+            Self {
+                range_start,
+                slots,
+                quarantine,
+            }
+        }
+
+        fn index(&self, addr: Ipv4Addr) -> Option<usize> {
+            let start: u32 = self.range_start.into();
+            let pos: u32 = addr.into();
+
+            pos.checked_sub(start)
+                .map(|offset| offset as usize)
+                .filter(|offset| *offset < self.slots.len())
+        }
+
+        fn addr_at(&self, index: usize) -> Ipv4Addr {
+            let start: u32 = self.range_start.into();
+
+            (start + index as u32).into()
+        }
+
+        fn reclaim_expired(&mut self) {
+            let now = Instant::now();
+
+            for slot in self.slots.iter_mut() {
+                let expired = match slot {
+                    Slot::Allocated(lease) => now > lease.expires,
+                    Slot::Unavailable { until } => now > *until,
+                    Slot::Available => false,
+                };
+
+                if expired {
+                    *slot = Slot::Available;
+                }
+            }
+        }
+
+        fn current_lease(&self, mac: &[u8; 6]) -> Option<Ipv4Addr> {
+            self.slots.iter().enumerate().find_map(|(index, slot)| {
+                matches!(slot, Slot::Allocated(lease) if lease.mac == *mac)
+                    .then(|| self.addr_at(index))
+            })
+        }
+
+        /// Whether `addr` can be (re-)leased to `mac` right now.
+        ///
+        /// Checks expiry/quarantine itself rather than assuming
+        /// `reclaim_expired` has already swept the slot, so it gives a
+        /// correct answer no matter which handler calls it.
+        fn is_available(&self, mac: &[u8; 6], addr: Ipv4Addr) -> bool {
+            let now = Instant::now();
+
+            match self.index(addr).map(|index| &self.slots[index]) {
+                Some(Slot::Available) => true,
+                Some(Slot::Allocated(lease)) => lease.mac == *mac || now > lease.expires,
+                Some(Slot::Unavailable { until }) => now > *until,
+                None => false,
+            }
+        }
+
+        /// Picks an address to offer, preferring (in order) the client's
+        /// prior lease, its requested address if still available, then the
+        /// lowest available address in the pool.
+        fn offer(&mut self, mac: &[u8; 6], requested: Option<Ipv4Addr>) -> Option<Ipv4Addr> {
+            self.reclaim_expired();
+
+            if let Some(addr) = self.current_lease(mac) {
+                return Some(addr);
+            }
+
+            if let Some(addr) = requested {
+                if self.is_available(mac, addr) {
+                    return Some(addr);
+                }
+            }
+
+            self.slots
+                .iter()
+                .enumerate()
+                .find_map(|(index, slot)| matches!(slot, Slot::Available).then(|| index))
+                .map(|index| self.addr_at(index))
+        }
+
+        fn allocate(&mut self, addr: Ipv4Addr, mac: [u8; 6], expires: Instant) -> bool {
+            self.release(&mac);
+
+            match self.index(addr) {
+                Some(index) => {
+                    self.slots[index] = Slot::Allocated(Lease { mac, expires });
+
+                    true
+                }
+                None => false,
+            }
+        }
+
+        fn release(&mut self, mac: &[u8; 6]) -> bool {
+            if let Some(index) = self
+                .slots
+                .iter()
+                .position(|slot| matches!(slot, Slot::Allocated(lease) if lease.mac == *mac))
+            {
+                self.slots[index] = Slot::Available;
+
+                true
+            } else {
+                false
+            }
+        }
+
+        fn decline(&mut self, addr: Ipv4Addr) {
+            if let Some(index) = self.index(addr) {
+                self.slots[index] = Slot::Unavailable {
+                    until: Instant::now() + self.quarantine,
+                };
+            }
+        }
+    }
+
+    /// A single lease assignment as persisted by a `LeaseStore`.
+    #[derive(Clone, Copy, Debug)]
+    pub struct LeaseInfo {
+        pub addr: Ipv4Addr,
+        pub mac: [u8; 6],
+        pub expires_secs: u32,
+    }
+
+    /// Persists the DHCP lease table across restarts.
+    ///
+    /// `Server` calls `store` whenever it grants a lease, `remove` when a
+    /// client releases or declines one or moves to a different address,
+    /// and `load` once via [`Server::init`] to repopulate its
+    /// in-memory lease table, so a device backing this with flash/NVS won't
+    /// re-offer an address it already promised to another MAC after a power
+    /// cycle. Leases that expire are reclaimed in memory only; `remove` is
+    /// not called for them. `N` must be at least as large as the `Server`'s address range
+    /// (`range_end - range_start + 1`), since that's what sizes its
+    /// `AddressPool` and therefore the most leases it can ever hold at once.
+    pub trait LeaseStore<const N: usize> {
+        type Error: Debug;
+
+        async fn load(&mut self) -> Result<heapless::Vec<LeaseInfo, N>, Self::Error>;
+
+        async fn store(&mut self, lease: LeaseInfo) -> Result<(), Self::Error>;
+
+        async fn remove(&mut self, addr: Ipv4Addr) -> Result<(), Self::Error>;
+    }
+
+    /// A `LeaseStore` that keeps no state, so leases are forgotten on
+    /// restart. This is the crate's previous, purely in-memory behavior and
+    /// the default for `Server`.
+    #[derive(Default)]
+    pub struct NoopLeaseStore;
+
+    impl<const N: usize> LeaseStore<N> for NoopLeaseStore {
+        type Error = core::convert::Infallible;
+
+        async fn load(&mut self) -> Result<heapless::Vec<LeaseInfo, N>, Self::Error> {
+            Ok(heapless::Vec::new())
+        }
+
+        async fn store(&mut self, _lease: LeaseInfo) -> Result<(), Self::Error> {
+            Ok(())
+        }
+
+        async fn remove(&mut self, _addr: Ipv4Addr) -> Result<(), Self::Error> {
+            Ok(())
+        }
+    }
+
+    pub struct Server<'a, const N: usize, S = NoopLeaseStore, D = NoopConflictDetector> {
         ip: Ipv4Addr,
         gateways: heapless::Vec<Ipv4Addr, 1>,
         subnet: Option<Ipv4Addr>,
         dns: heapless::Vec<Ipv4Addr, 2>,
-        range_start: Ipv4Addr,
-        range_end: Ipv4Addr,
         lease_duration: Duration,
-        leases: heapless::LinearMap<Ipv4Addr, Lease, N>,
+        extra_options: &'a [DhcpOption<'a>],
+        pool: AddressPool<N>,
+        store: S,
+        detector: D,
+    }
+
+    impl<'a, const N: usize> Server<'a, N> {
+        pub fn new(conf: &Configuration<'a>) -> Self {
+            Self::with_store(conf, NoopLeaseStore)
+        }
+    }
+
+    impl<'a, const N: usize, S> Server<'a, N, S>
+    where
+        S: LeaseStore<N>,
+    {
+        pub fn with_store(conf: &Configuration<'a>, store: S) -> Self {
+            Self::with_store_and_detector(conf, store, NoopConflictDetector)
+        }
     }
 
-    impl<const N: usize> Server<N> {
-        pub fn new(conf: &Configuration) -> Self {
+    impl<'a, const N: usize, S, D> Server<'a, N, S, D>
+    where
+        S: LeaseStore<N>,
+        D: ConflictDetector,
+    {
+        pub fn with_store_and_detector(conf: &Configuration<'a>, store: S, detector: D) -> Self {
             Self {
                 ip: conf.ip,
                 gateways: conf.gateway.iter().cloned().collect(),
                 subnet: conf.subnet,
                 dns: conf.dns1.iter().chain(conf.dns2.iter()).cloned().collect(),
-                range_start: conf.range_start,
-                range_end: conf.range_end,
                 lease_duration: conf.lease_duration,
-                leases: heapless::LinearMap::new(),
+                extra_options: conf.extra_options,
+                pool: AddressPool::new(conf.range_start, conf.range_end, conf.decline_quarantine),
+                store,
+                detector,
+            }
+        }
+
+        /// Repopulates the in-memory address pool from the `LeaseStore`.
+        /// Call this once on startup, before `run`.
+        pub async fn init(&mut self) -> Result<(), S::Error> {
+            for lease in self.store.load().await? {
+                self.pool.allocate(
+                    lease.addr,
+                    lease.mac,
+                    Instant::now() + Duration::from_secs(lease.expires_secs as _),
+                );
             }
+
+            Ok(())
         }
 
         pub async fn run<U: UdpStack>(
@@ -320,10 +895,19 @@ pub mod server {
                     });
 
                     if server_identifier == Some(self.ip)
-                        || server_identifier.is_none() && matches!(mt, MessageType::Discover)
+                        || server_identifier.is_none()
+                            && matches!(mt, MessageType::Discover | MessageType::Inform)
                     {
                         let mut opt_buf = Options::buf();
 
+                        let requested_params = request.options.iter().find_map(|option| {
+                            if let DhcpOption::ParameterRequestList(list) = option {
+                                Some(list)
+                            } else {
+                                None
+                            }
+                        });
+
                         let reply = match mt {
                             MessageType::Discover => {
                                 let requested_ip = request.options.iter().find_map(|option| {
@@ -334,18 +918,15 @@ pub mod server {
                                     }
                                 });
 
-                                let ip = requested_ip
-                                    .and_then(|ip| {
-                                        self.is_available(&request.chaddr, ip).then_some(ip)
-                                    })
-                                    .or_else(|| self.current_lease(&request.chaddr))
-                                    .or_else(|| self.available());
+                                let ip = self.pool.offer(&request.chaddr, requested_ip);
 
                                 ip.map(|ip| {
                                     self.reply_to(
                                         &request,
                                         MessageType::Offer,
                                         Some(ip),
+                                        Some(self.lease_duration.as_secs() as _),
+                                        requested_params,
                                         &mut opt_buf,
                                     )
                                 })
@@ -363,18 +944,39 @@ pub mod server {
                                     })
                                     .unwrap_or(request.ciaddr);
 
+                                // A client renewing/rebinding the address it
+                                // already holds is using `ip` itself right
+                                // now, so a conflict probe would just see
+                                // the client's own traffic; only probe when
+                                // we're about to allocate `ip` fresh.
+                                let is_renewal =
+                                    self.pool.current_lease(&request.chaddr) == Some(ip);
+
+                                let in_use = !is_renewal
+                                    && self.pool.is_available(&request.chaddr, ip)
+                                    && self.detector.is_in_use(ip).await;
+
+                                if in_use {
+                                    self.pool.decline(ip);
+                                }
+
                                 Some(
-                                    if self.is_available(&request.chaddr, ip)
-                                        && self.add_lease(
-                                            ip,
-                                            request.chaddr,
-                                            Instant::now() + self.lease_duration,
-                                        )
+                                    if !in_use
+                                        && self.pool.is_available(&request.chaddr, ip)
+                                        && self
+                                            .add_lease(
+                                                ip,
+                                                request.chaddr,
+                                                Instant::now() + self.lease_duration,
+                                            )
+                                            .await
                                     {
                                         self.reply_to(
                                             &request,
                                             MessageType::Ack,
                                             Some(ip),
+                                            Some(self.lease_duration.as_secs() as _),
+                                            requested_params,
                                             &mut opt_buf,
                                         )
                                     } else {
@@ -382,13 +984,50 @@ pub mod server {
                                             &request,
                                             MessageType::Nak,
                                             None,
+                                            None,
+                                            requested_params,
                                             &mut opt_buf,
                                         )
                                     },
                                 )
                             }
-                            MessageType::Decline | MessageType::Release => {
-                                self.remove_lease(&request.chaddr);
+                            MessageType::Inform => Some(self.reply_to(
+                                &request,
+                                MessageType::Ack,
+                                None,
+                                None,
+                                requested_params,
+                                &mut opt_buf,
+                            )),
+                            MessageType::Decline => {
+                                let declined_ip = request
+                                    .options
+                                    .iter()
+                                    .find_map(|option| {
+                                        if let DhcpOption::RequestedIpAddress(ip) = option {
+                                            Some(ip)
+                                        } else {
+                                            None
+                                        }
+                                    })
+                                    .unwrap_or(request.ciaddr);
+
+                                // Only quarantine addresses this client could
+                                // plausibly have been offered: anyone on the
+                                // segment can broadcast a DHCPDECLINE with a
+                                // spoofed chaddr, and server_identifier alone
+                                // doesn't stop that, so don't let a decline
+                                // for an address actively leased to a
+                                // *different* MAC take it out of the pool.
+                                if self.pool.is_available(&request.chaddr, declined_ip) {
+                                    self.remove_lease(&request.chaddr).await;
+                                    self.pool.decline(declined_ip);
+                                }
+
+                                None
+                            }
+                            MessageType::Release => {
+                                self.remove_lease(&request.chaddr).await;
 
                                 None
                             }
@@ -415,81 +1054,204 @@ pub mod server {
             request: &Packet<'_>,
             mt: MessageType,
             ip: Option<Ipv4Addr>,
-            buf: &'a mut [DhcpOption<'a>],
+            lease_secs: Option<u32>,
+            requested_params: Option<&[u8]>,
+            buf: &'a mut [u8],
         ) -> Packet<'a> {
             request.new_reply(
                 ip,
                 request.options.reply(
                     mt,
                     self.ip,
-                    self.lease_duration.as_secs() as _,
+                    lease_secs,
                     &self.gateways,
                     self.subnet,
                     &self.dns,
+                    requested_params,
+                    self.extra_options,
                     buf,
                 ),
             )
         }
 
-        fn is_available(&self, mac: &[u8; 6], addr: Ipv4Addr) -> bool {
-            let pos: u32 = addr.into();
-
-            let start: u32 = self.range_start.into();
-            let end: u32 = self.range_end.into();
-
-            pos >= start
-                && pos <= end
-                && match self.leases.get(&addr) {
-                    Some(lease) => lease.mac == *mac || Instant::now() > lease.expires,
-                    None => true,
-                }
-        }
+        async fn add_lease(&mut self, addr: Ipv4Addr, mac: [u8; 6], expires: Instant) -> bool {
+            // `allocate` drops any other address `mac` held, so drop it from
+            // the store as well.
+            if let Some(previous) = self.pool.current_lease(&mac).filter(|prev| *prev != addr) {
+                let _ = self.store.remove(previous).await;
+            }
 
-        fn available(&mut self) -> Option<Ipv4Addr> {
-            let start: u32 = self.range_start.into();
-            let end: u32 = self.range_end.into();
+            if self.pool.allocate(addr, mac, expires) {
+                let expires_secs = (expires - Instant::now()).as_secs() as u32;
 
-            for pos in start..end + 1 {
-                let addr = pos.into();
+                let _ = self
+                    .store
+                    .store(LeaseInfo {
+                        addr,
+                        mac,
+                        expires_secs,
+                    })
+                    .await;
 
-                if !self.leases.contains_key(&addr) {
-                    return Some(addr);
-                }
+                true
+            } else {
+                false
             }
+        }
 
-            if let Some(addr) = self
-                .leases
-                .iter()
-                .find_map(|(addr, lease)| (Instant::now() > lease.expires).then_some(*addr))
-            {
-                self.leases.remove(&addr);
+        async fn remove_lease(&mut self, mac: &[u8; 6]) -> bool {
+            if let Some(addr) = self.pool.current_lease(mac) {
+                self.pool.release(mac);
+                let _ = self.store.remove(addr).await;
 
-                Some(addr)
+                true
             } else {
-                None
+                false
             }
         }
+    }
 
-        fn current_lease(&self, mac: &[u8; 6]) -> Option<Ipv4Addr> {
-            self.leases
-                .iter()
-                .find_map(|(addr, lease)| (lease.mac == *mac).then_some(*addr))
+    // Exercises the `AddressPool` state machine directly, since the
+    // Request/Decline handlers above are thin wrappers around it and a
+    // regression here (e.g. the truncation and stale-expiry bugs fixed
+    // previously) would otherwise only surface as a flaky integration
+    // failure, if at all.
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        const MAC_A: [u8; 6] = [0, 1, 2, 3, 4, 5];
+        const MAC_B: [u8; 6] = [0, 1, 2, 3, 4, 6];
+
+        fn pool() -> AddressPool<4> {
+            AddressPool::new(
+                Ipv4Addr::new(192, 168, 1, 10),
+                Ipv4Addr::new(192, 168, 1, 13),
+                Duration::from_secs(30),
+            )
         }
 
-        fn add_lease(&mut self, addr: Ipv4Addr, mac: [u8; 6], expires: Instant) -> bool {
-            self.remove_lease(&mac);
+        #[test]
+        fn offer_picks_lowest_available_address() {
+            let mut pool = pool();
 
-            self.leases.insert(addr, Lease { mac, expires }).is_ok()
+            assert_eq!(
+                pool.offer(&MAC_A, None),
+                Some(Ipv4Addr::new(192, 168, 1, 10))
+            );
         }
 
-        fn remove_lease(&mut self, mac: &[u8; 6]) -> bool {
-            if let Some(addr) = self.current_lease(mac) {
-                self.leases.remove(&addr);
+        #[test]
+        fn offer_sticks_to_an_existing_lease() {
+            let mut pool = pool();
+            let addr = Ipv4Addr::new(192, 168, 1, 12);
 
-                true
-            } else {
-                false
-            }
+            assert!(pool.allocate(addr, MAC_A, Instant::now() + Duration::from_secs(60)));
+            assert_eq!(pool.offer(&MAC_A, None), Some(addr));
+        }
+
+        #[test]
+        fn allocate_then_release_frees_the_slot_for_others() {
+            let mut pool = pool();
+            let addr = Ipv4Addr::new(192, 168, 1, 10);
+
+            assert!(pool.allocate(addr, MAC_A, Instant::now() + Duration::from_secs(60)));
+            assert!(!pool.is_available(&MAC_B, addr));
+
+            assert!(pool.release(&MAC_A));
+            assert!(pool.is_available(&MAC_B, addr));
+        }
+
+        #[test]
+        fn is_available_lets_the_current_holder_renew_but_not_anyone_else() {
+            let mut pool = pool();
+            let addr = Ipv4Addr::new(192, 168, 1, 10);
+
+            assert!(pool.allocate(addr, MAC_A, Instant::now() + Duration::from_secs(60)));
+
+            assert!(
+                pool.is_available(&MAC_A, addr),
+                "the MAC holding the lease must be able to renew it"
+            );
+            assert!(
+                !pool.is_available(&MAC_B, addr),
+                "a different MAC must not be able to take over a live lease"
+            );
+        }
+
+        #[test]
+        fn expired_lease_becomes_available_again() {
+            let mut pool = pool();
+            let addr = Ipv4Addr::new(192, 168, 1, 10);
+
+            assert!(pool.allocate(addr, MAC_A, Instant::now() - Duration::from_secs(1)));
+
+            assert!(
+                pool.is_available(&MAC_B, addr),
+                "an expired lease must not block a new client"
+            );
+
+            assert_eq!(pool.offer(&MAC_B, None), Some(addr));
+        }
+
+        #[test]
+        fn decline_quarantines_the_address_until_it_expires() {
+            let mut pool = AddressPool::<4>::new(
+                Ipv4Addr::new(192, 168, 1, 10),
+                Ipv4Addr::new(192, 168, 1, 13),
+                Duration::from_secs(30),
+            );
+            let addr = Ipv4Addr::new(192, 168, 1, 10);
+
+            pool.decline(addr);
+            assert!(
+                !pool.is_available(&MAC_A, addr),
+                "a just-declined address must not be handed straight back out"
+            );
+
+            // Move the mock clock past the 30s quarantine, so the address is
+            // past its `until` deadline and is available again.
+            embassy_time::MockDriver::get().advance(Duration::from_secs(31));
+            assert!(pool.is_available(&MAC_A, addr));
+        }
+
+        #[test]
+        fn allocating_a_new_address_releases_the_macs_old_one() {
+            let mut pool = pool();
+            let first = Ipv4Addr::new(192, 168, 1, 10);
+            let second = Ipv4Addr::new(192, 168, 1, 11);
+
+            assert!(pool.allocate(first, MAC_A, Instant::now() + Duration::from_secs(60)));
+            assert!(pool.allocate(second, MAC_A, Instant::now() + Duration::from_secs(60)));
+
+            assert!(
+                pool.is_available(&MAC_B, first),
+                "a MAC can only hold one lease at a time"
+            );
+            assert_eq!(pool.current_lease(&MAC_A), Some(second));
+        }
+
+        // The Request handler only probes for conflicts when
+        // `pool.current_lease(&chaddr) != Some(ip)`; this pins down that
+        // `current_lease` reports a renewing client's own address so that
+        // check actually skips the probe instead of firing on every renewal.
+        #[test]
+        fn current_lease_matches_a_renewing_clients_own_address() {
+            let mut pool = pool();
+            let addr = Ipv4Addr::new(192, 168, 1, 10);
+
+            assert!(pool.allocate(addr, MAC_A, Instant::now() + Duration::from_secs(60)));
+
+            assert_eq!(
+                pool.current_lease(&MAC_A),
+                Some(addr),
+                "a renewing client's own address must read back as its current lease"
+            );
+            assert_ne!(
+                pool.current_lease(&MAC_B),
+                Some(addr),
+                "a different MAC must never be reported as already holding this lease"
+            );
         }
     }
 }
\ No newline at end of file
@@ -0,0 +1,583 @@
+//! Raw DHCP (RFC 2131) wire format: message encode/decode and the TLV
+//! options list. Shared by the client and server in `crate::asynch::dhcp`,
+//! which handle the protocol state machines on top of this.
+
+use embedded_nal_async::Ipv4Addr;
+
+const MAGIC_COOKIE: [u8; 4] = [99, 130, 83, 99];
+
+/// Fixed-size portion of a message, before the magic cookie and options
+/// (RFC 2131 section 2).
+const HEADER_LEN: usize = 236;
+
+const OP_REQUEST: u8 = 1;
+const OP_REPLY: u8 = 2;
+
+const HTYPE_ETHERNET: u8 = 1;
+const HLEN_ETHERNET: u8 = 6;
+
+/// Scratch size for the options this crate ever builds: message type,
+/// server identifier, lease/renewal/rebinding times, a handful of
+/// servable options and their End marker. Comfortably under the classic
+/// 312-byte BOOTP vendor-extensions field.
+pub const OPTIONS_BUF_LEN: usize = 128;
+
+#[derive(Debug)]
+pub enum Error {
+    /// Shorter than a fixed header, or missing the magic cookie.
+    Truncated,
+    /// Not enough room in the destination buffer to encode the message.
+    BufferTooSmall,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum MessageType {
+    Discover,
+    Offer,
+    Request,
+    Decline,
+    Ack,
+    Nak,
+    Release,
+    Inform,
+}
+
+impl MessageType {
+    fn code(self) -> u8 {
+        match self {
+            Self::Discover => 1,
+            Self::Offer => 2,
+            Self::Request => 3,
+            Self::Decline => 4,
+            Self::Ack => 5,
+            Self::Nak => 6,
+            Self::Release => 7,
+            Self::Inform => 8,
+        }
+    }
+
+    fn from_code(code: u8) -> Option<Self> {
+        Some(match code {
+            1 => Self::Discover,
+            2 => Self::Offer,
+            3 => Self::Request,
+            4 => Self::Decline,
+            5 => Self::Ack,
+            6 => Self::Nak,
+            7 => Self::Release,
+            8 => Self::Inform,
+            _ => return None,
+        })
+    }
+}
+
+const OPT_SUBNET_MASK: u8 = 1;
+const OPT_ROUTER: u8 = 3;
+const OPT_DNS: u8 = 6;
+const OPT_REQUESTED_IP: u8 = 50;
+const OPT_LEASE_TIME: u8 = 51;
+const OPT_MESSAGE_TYPE: u8 = 53;
+const OPT_SERVER_IDENTIFIER: u8 = 54;
+const OPT_PARAMETER_REQUEST_LIST: u8 = 55;
+const OPT_RENEWAL_TIME: u8 = 58;
+const OPT_REBINDING_TIME: u8 = 59;
+const OPT_PAD: u8 = 0;
+const OPT_END: u8 = 255;
+
+/// The default Parameter Request List `reply()` serves by when a client
+/// didn't send one of its own: the servable options a typical client
+/// needs (subnet mask, router, DNS, the renewal/rebinding timers), in the
+/// order they're written out. `extra_options` never falls back to this,
+/// since those are only meant for clients that explicitly asked for them.
+const PRL_DEFAULT: [u8; 5] = [
+    OPT_SUBNET_MASK,
+    OPT_ROUTER,
+    OPT_DNS,
+    OPT_RENEWAL_TIME,
+    OPT_REBINDING_TIME,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum DhcpOption<'a> {
+    MessageType(MessageType),
+    ServerIdentifier(Ipv4Addr),
+    RequestedIpAddress(Ipv4Addr),
+    IpAddressLeaseTime(u32),
+    RenewalTimeValue(u32),
+    RebindingTimeValue(u32),
+    SubnetMask(Ipv4Addr),
+    Router(Ipv4Addr),
+    DomainNameServer(Ipv4Addr),
+    ParameterRequestList(&'a [u8]),
+    /// Any option this crate doesn't model explicitly, so callers can
+    /// serve things like domain name or NTP servers via `extra_options`
+    /// without this enum growing a variant per use case.
+    Other { code: u8, data: &'a [u8] },
+}
+
+impl<'a> DhcpOption<'a> {
+    fn code(&self) -> u8 {
+        match self {
+            Self::MessageType(_) => OPT_MESSAGE_TYPE,
+            Self::ServerIdentifier(_) => OPT_SERVER_IDENTIFIER,
+            Self::RequestedIpAddress(_) => OPT_REQUESTED_IP,
+            Self::IpAddressLeaseTime(_) => OPT_LEASE_TIME,
+            Self::RenewalTimeValue(_) => OPT_RENEWAL_TIME,
+            Self::RebindingTimeValue(_) => OPT_REBINDING_TIME,
+            Self::SubnetMask(_) => OPT_SUBNET_MASK,
+            Self::Router(_) => OPT_ROUTER,
+            Self::DomainNameServer(_) => OPT_DNS,
+            Self::ParameterRequestList(_) => OPT_PARAMETER_REQUEST_LIST,
+            Self::Other { code, .. } => *code,
+        }
+    }
+
+    /// Writes this option's code/length/value TLV into `buf`, returning
+    /// the number of bytes written, or `None` if it doesn't fit.
+    fn encode(&self, buf: &mut [u8]) -> Option<usize> {
+        fn write_tlv(buf: &mut [u8], code: u8, data: &[u8]) -> Option<usize> {
+            let len = 2 + data.len();
+
+            if buf.len() < len || data.len() > u8::MAX as usize {
+                return None;
+            }
+
+            buf[0] = code;
+            buf[1] = data.len() as u8;
+            buf[2..len].copy_from_slice(data);
+
+            Some(len)
+        }
+
+        match self {
+            Self::MessageType(mt) => write_tlv(buf, self.code(), &[mt.code()]),
+            Self::ServerIdentifier(ip)
+            | Self::RequestedIpAddress(ip)
+            | Self::SubnetMask(ip)
+            | Self::Router(ip)
+            | Self::DomainNameServer(ip) => write_tlv(buf, self.code(), &ip.octets()),
+            Self::IpAddressLeaseTime(secs)
+            | Self::RenewalTimeValue(secs)
+            | Self::RebindingTimeValue(secs) => write_tlv(buf, self.code(), &secs.to_be_bytes()),
+            Self::ParameterRequestList(codes) => write_tlv(buf, self.code(), codes),
+            Self::Other { data, .. } => write_tlv(buf, self.code(), data),
+        }
+    }
+
+    fn decode(code: u8, data: &'a [u8]) -> Self {
+        fn ip(data: &[u8]) -> Option<Ipv4Addr> {
+            <[u8; 4]>::try_from(data).ok().map(Ipv4Addr::from)
+        }
+
+        fn u32_be(data: &[u8]) -> Option<u32> {
+            <[u8; 4]>::try_from(data).ok().map(u32::from_be_bytes)
+        }
+
+        match code {
+            OPT_MESSAGE_TYPE => data
+                .first()
+                .copied()
+                .and_then(MessageType::from_code)
+                .map(Self::MessageType),
+            OPT_SERVER_IDENTIFIER => ip(data).map(Self::ServerIdentifier),
+            OPT_REQUESTED_IP => ip(data).map(Self::RequestedIpAddress),
+            OPT_LEASE_TIME => u32_be(data).map(Self::IpAddressLeaseTime),
+            OPT_RENEWAL_TIME => u32_be(data).map(Self::RenewalTimeValue),
+            OPT_REBINDING_TIME => u32_be(data).map(Self::RebindingTimeValue),
+            OPT_SUBNET_MASK => ip(data).map(Self::SubnetMask),
+            OPT_ROUTER => ip(data).map(Self::Router),
+            OPT_DNS => ip(data).map(Self::DomainNameServer),
+            OPT_PARAMETER_REQUEST_LIST => Some(Self::ParameterRequestList(data)),
+            _ => None,
+        }
+        .unwrap_or(Self::Other { code, data })
+    }
+}
+
+/// A list of DHCP options.
+///
+/// Backed directly by raw bytes rather than a parsed array, so the same
+/// type serves both directions: decoding borrows straight from the
+/// received packet, and building borrows a scratch buffer the caller
+/// owns (`Options::buf()`) and writes TLVs into as it goes. Individual
+/// options are only decoded on demand, via `iter()`.
+#[derive(Clone, Copy)]
+pub struct Options<'a> {
+    buf: &'a [u8],
+}
+
+impl<'a> Options<'a> {
+    /// Scratch storage for any message this crate builds; pass `&mut` a
+    /// value of this to `discover`/`request`/`release`/`decline`/`inform`/
+    /// `reply`.
+    pub fn buf() -> [u8; OPTIONS_BUF_LEN] {
+        [0; OPTIONS_BUF_LEN]
+    }
+
+    fn from_bytes(buf: &'a [u8]) -> Self {
+        Self { buf }
+    }
+
+    pub fn iter(&self) -> OptionsIter<'a> {
+        OptionsIter { rest: self.buf }
+    }
+
+    fn write(buf: &mut [u8], len: &mut usize, option: &DhcpOption<'_>) {
+        if let Some(n) = option.encode(&mut buf[*len..]) {
+            *len += n;
+        }
+    }
+
+    fn terminate(buf: &'a mut [u8], len: usize) -> Self {
+        let len = match buf.get_mut(len) {
+            Some(end) => {
+                *end = OPT_END;
+                len + 1
+            }
+            None => len,
+        };
+
+        Self { buf: &buf[..len] }
+    }
+
+    fn build(buf: &'a mut [u8], options: &[DhcpOption<'_>]) -> Self {
+        let mut len = 0;
+
+        for option in options {
+            Self::write(buf, &mut len, option);
+        }
+
+        Self::terminate(buf, len)
+    }
+
+    pub fn discover(requested_ip: Option<Ipv4Addr>, buf: &'a mut [u8]) -> Self {
+        let mut options: heapless::Vec<DhcpOption, 2> = heapless::Vec::new();
+        let _ = options.push(DhcpOption::MessageType(MessageType::Discover));
+
+        if let Some(ip) = requested_ip {
+            let _ = options.push(DhcpOption::RequestedIpAddress(ip));
+        }
+
+        Self::build(buf, &options)
+    }
+
+    pub fn request(requested_ip: Ipv4Addr, buf: &'a mut [u8]) -> Self {
+        Self::build(
+            buf,
+            &[
+                DhcpOption::MessageType(MessageType::Request),
+                DhcpOption::RequestedIpAddress(requested_ip),
+            ],
+        )
+    }
+
+    pub fn release(buf: &'a mut [u8]) -> Self {
+        Self::build(buf, &[DhcpOption::MessageType(MessageType::Release)])
+    }
+
+    pub fn decline(buf: &'a mut [u8]) -> Self {
+        Self::build(buf, &[DhcpOption::MessageType(MessageType::Decline)])
+    }
+
+    /// Builds an INFORM's options: just the message type and a Parameter
+    /// Request List, so a host with a statically-configured address can
+    /// still ask the server for router/subnet-mask/DNS the same way a
+    /// `discover`/`request` reply would carry them.
+    pub fn inform(buf: &'a mut [u8]) -> Self {
+        Self::build(
+            buf,
+            &[
+                DhcpOption::MessageType(MessageType::Inform),
+                DhcpOption::ParameterRequestList(&PRL_DEFAULT),
+            ],
+        )
+    }
+
+    /// Builds a server's reply options: message type, server identifier,
+    /// lease time, then the servable options filtered and ordered by the
+    /// client's Parameter Request List (or `PRL_DEFAULT`'s order, if the
+    /// client didn't send one), splicing in any matching `extra_options`.
+    #[allow(clippy::too_many_arguments)]
+    pub fn reply(
+        &self,
+        mt: MessageType,
+        server_ip: Ipv4Addr,
+        lease_secs: Option<u32>,
+        gateways: &[Ipv4Addr],
+        subnet: Option<Ipv4Addr>,
+        dns: &[Ipv4Addr],
+        requested_params: Option<&[u8]>,
+        extra_options: &[DhcpOption<'_>],
+        buf: &'a mut [u8],
+    ) -> Options<'a> {
+        let prl = requested_params.unwrap_or(&PRL_DEFAULT);
+
+        let mut len = 0;
+
+        Self::write(buf, &mut len, &DhcpOption::MessageType(mt));
+        Self::write(buf, &mut len, &DhcpOption::ServerIdentifier(server_ip));
+
+        if let Some(secs) = lease_secs {
+            Self::write(buf, &mut len, &DhcpOption::IpAddressLeaseTime(secs));
+        }
+
+        for &code in prl {
+            match code {
+                OPT_SUBNET_MASK => {
+                    if let Some(ip) = subnet {
+                        Self::write(buf, &mut len, &DhcpOption::SubnetMask(ip));
+                    }
+                }
+                OPT_ROUTER => {
+                    if let Some(&ip) = gateways.first() {
+                        Self::write(buf, &mut len, &DhcpOption::Router(ip));
+                    }
+                }
+                OPT_DNS => {
+                    for &ip in dns {
+                        Self::write(buf, &mut len, &DhcpOption::DomainNameServer(ip));
+                    }
+                }
+                OPT_RENEWAL_TIME => {
+                    if let Some(secs) = lease_secs {
+                        Self::write(buf, &mut len, &DhcpOption::RenewalTimeValue(secs / 2));
+                    }
+                }
+                OPT_REBINDING_TIME => {
+                    if let Some(secs) = lease_secs {
+                        Self::write(
+                            buf,
+                            &mut len,
+                            &DhcpOption::RebindingTimeValue(secs * 7 / 8),
+                        );
+                    }
+                }
+                code => {
+                    if let Some(option) = extra_options.iter().find(|o| o.code() == code) {
+                        Self::write(buf, &mut len, option);
+                    }
+                }
+            }
+        }
+
+        Self::terminate(buf, len)
+    }
+}
+
+pub struct OptionsIter<'a> {
+    rest: &'a [u8],
+}
+
+impl<'a> Iterator for OptionsIter<'a> {
+    type Item = DhcpOption<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            let (&code, rest) = self.rest.split_first()?;
+
+            if code == OPT_PAD {
+                self.rest = rest;
+                continue;
+            }
+
+            if code == OPT_END {
+                self.rest = &[];
+                return None;
+            }
+
+            let (&len, rest) = rest.split_first()?;
+            let len = len as usize;
+
+            if rest.len() < len {
+                self.rest = &[];
+                return None;
+            }
+
+            let (data, rest) = rest.split_at(len);
+            self.rest = rest;
+
+            return Some(DhcpOption::decode(code, data));
+        }
+    }
+}
+
+/// A decoded or to-be-encoded DHCP message.
+#[derive(Clone)]
+pub struct Packet<'a> {
+    pub reply: bool,
+    pub xid: u32,
+    pub secs: u16,
+    pub chaddr: [u8; 6],
+    pub ciaddr: Ipv4Addr,
+    pub yiaddr: Ipv4Addr,
+    pub options: Options<'a>,
+}
+
+impl<'a> Packet<'a> {
+    pub fn new_request(
+        chaddr: [u8; 6],
+        xid: u32,
+        secs: u16,
+        ciaddr: Option<Ipv4Addr>,
+        options: Options<'a>,
+    ) -> Self {
+        Self {
+            reply: false,
+            xid,
+            secs,
+            chaddr,
+            ciaddr: ciaddr.unwrap_or(Ipv4Addr::new(0, 0, 0, 0)),
+            yiaddr: Ipv4Addr::new(0, 0, 0, 0),
+            options,
+        }
+    }
+
+    pub fn new_reply(&self, yiaddr: Option<Ipv4Addr>, options: Options<'a>) -> Packet<'a> {
+        Packet {
+            reply: true,
+            xid: self.xid,
+            secs: self.secs,
+            chaddr: self.chaddr,
+            ciaddr: self.ciaddr,
+            yiaddr: yiaddr.unwrap_or(Ipv4Addr::new(0, 0, 0, 0)),
+            options,
+        }
+    }
+
+    pub fn encode(&self, buf: &mut [u8]) -> Result<&[u8], Error> {
+        let total = HEADER_LEN + MAGIC_COOKIE.len() + self.options.buf.len();
+
+        if buf.len() < total {
+            return Err(Error::BufferTooSmall);
+        }
+
+        buf[..HEADER_LEN].fill(0);
+
+        buf[0] = if self.reply { OP_REPLY } else { OP_REQUEST };
+        buf[1] = HTYPE_ETHERNET;
+        buf[2] = HLEN_ETHERNET;
+        buf[4..8].copy_from_slice(&self.xid.to_be_bytes());
+        buf[8..10].copy_from_slice(&self.secs.to_be_bytes());
+        buf[12..16].copy_from_slice(&self.ciaddr.octets());
+        buf[16..20].copy_from_slice(&self.yiaddr.octets());
+        buf[28..34].copy_from_slice(&self.chaddr);
+
+        let mut pos = HEADER_LEN;
+        buf[pos..pos + MAGIC_COOKIE.len()].copy_from_slice(&MAGIC_COOKIE);
+        pos += MAGIC_COOKIE.len();
+
+        buf[pos..pos + self.options.buf.len()].copy_from_slice(self.options.buf);
+        pos += self.options.buf.len();
+
+        Ok(&buf[..pos])
+    }
+
+    pub fn decode(buf: &'a [u8]) -> Result<Self, Error> {
+        if buf.len() < HEADER_LEN + MAGIC_COOKIE.len() {
+            return Err(Error::Truncated);
+        }
+
+        if buf[HEADER_LEN..HEADER_LEN + MAGIC_COOKIE.len()] != MAGIC_COOKIE {
+            return Err(Error::Truncated);
+        }
+
+        Ok(Self {
+            reply: buf[0] == OP_REPLY,
+            xid: u32::from_be_bytes(buf[4..8].try_into().unwrap()),
+            secs: u16::from_be_bytes(buf[8..10].try_into().unwrap()),
+            ciaddr: Ipv4Addr::from(<[u8; 4]>::try_from(&buf[12..16]).unwrap()),
+            yiaddr: Ipv4Addr::from(<[u8; 4]>::try_from(&buf[16..20]).unwrap()),
+            chaddr: buf[28..34].try_into().unwrap(),
+            options: Options::from_bytes(&buf[HEADER_LEN + MAGIC_COOKIE.len()..]),
+        })
+    }
+
+    /// Matches this packet against an in-flight request's `mac`/`xid` and,
+    /// if it's a genuine reply to it, extracts the settings a client needs
+    /// to act on.
+    pub fn parse_reply(&self, mac: &[u8; 6], xid: u32) -> Option<(MessageType, Settings)> {
+        if !self.reply || self.xid != xid || self.chaddr != *mac {
+            return None;
+        }
+
+        let unspecified = Ipv4Addr::new(0, 0, 0, 0);
+
+        let mt = self.options.iter().find_map(|option| match option {
+            DhcpOption::MessageType(mt) => Some(mt),
+            _ => None,
+        })?;
+
+        let server_ip = self.options.iter().find_map(|option| match option {
+            DhcpOption::ServerIdentifier(ip) => Some(ip),
+            _ => None,
+        });
+
+        let lease_time_secs = self.options.iter().find_map(|option| match option {
+            DhcpOption::IpAddressLeaseTime(secs) => Some(secs),
+            _ => None,
+        });
+
+        let renewal_time_secs = self.options.iter().find_map(|option| match option {
+            DhcpOption::RenewalTimeValue(secs) => Some(secs),
+            _ => None,
+        });
+
+        let rebinding_time_secs = self.options.iter().find_map(|option| match option {
+            DhcpOption::RebindingTimeValue(secs) => Some(secs),
+            _ => None,
+        });
+
+        Some((
+            mt,
+            Settings {
+                // INFORM replies carry the client's own address in
+                // `ciaddr` and leave `yiaddr` unset, since the server
+                // isn't assigning anything.
+                ip: if self.yiaddr != unspecified {
+                    self.yiaddr
+                } else {
+                    self.ciaddr
+                },
+                server_ip,
+                lease_time_secs,
+                renewal_time_secs,
+                rebinding_time_secs,
+            },
+        ))
+    }
+}
+
+/// The lease/configuration state a client tracks for an address it was
+/// granted (or, for `inform`, for a statically-configured one).
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Settings {
+    pub ip: Ipv4Addr,
+    pub server_ip: Option<Ipv4Addr>,
+    pub lease_time_secs: Option<u32>,
+    /// T1, parsed from option 58: when to start unicasting a renewal.
+    /// `renew_lease` falls back to half the lease time if the server
+    /// didn't send one.
+    pub renewal_time_secs: Option<u32>,
+    /// T2, parsed from option 59: when to fall back to broadcasting a
+    /// rebind. `renew_lease` falls back to 7/8 of the lease time if the
+    /// server didn't send one.
+    pub rebinding_time_secs: Option<u32>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn inform_attaches_a_parameter_request_list() {
+        let mut buf = Options::buf();
+        let options = Options::inform(&mut buf);
+
+        assert!(
+            options
+                .iter()
+                .any(|option| matches!(option, DhcpOption::ParameterRequestList(_))),
+            "an INFORM must carry a PRL or the server has nothing to filter its reply by"
+        );
+    }
+}